@@ -1,13 +1,150 @@
 // Copyright 2020 Joyent, Inc.
 
+use ring::rand::SystemRandom;
+use ring::signature::{self, RsaKeyPair};
 use slog::Logger;
-use std::time::Duration;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use reqwest::{Client, IntoUrl, Response};
-// Use old-style Hyper headers until they put them back in.
-use reqwest::hyper_011::header::{Accept, ContentType, Headers};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+use reqwest::r#async::{Client as AsyncClient, Response as AsyncResponse};
+use reqwest::{IntoUrl, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use tokio::runtime::Runtime;
+use url::form_urlencoded;
+
+/// Errors returned by the SAPI client.
+#[derive(Debug)]
+pub enum SapiError {
+    /// The request could not be sent, or the response could not be read.
+    Transport(reqwest::Error),
+    /// The response body was read but could not be deserialized into the
+    /// expected type, e.g. SAPI returned a 200 with an unexpected shape.
+    Deserialize(reqwest::Error),
+    /// SAPI responded with a non-2xx status other than 404.
+    UnexpectedStatus {
+        status: StatusCode,
+        body: String,
+        /// The server-requested backoff from a `Retry-After` header, if any.
+        retry_after: Option<Duration>,
+    },
+    /// SAPI responded 404 Not Found.
+    NotFound,
+    /// The request could not be signed with the configured credential.
+    Signing(String),
+}
+
+impl fmt::Display for SapiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SapiError::Transport(e) => write!(f, "transport error: {}", e),
+            SapiError::Deserialize(e) => write!(f, "failed to deserialize response: {}", e),
+            SapiError::UnexpectedStatus { status, body, .. } => {
+                write!(f, "unexpected status {}: {}", status, body)
+            }
+            SapiError::NotFound => write!(f, "not found"),
+            SapiError::Signing(msg) => write!(f, "failed to sign request: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SapiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SapiError::Transport(e) => Some(e),
+            SapiError::Deserialize(e) => Some(e),
+            SapiError::UnexpectedStatus { .. } | SapiError::NotFound | SapiError::Signing(_) => {
+                None
+            }
+        }
+    }
+}
+
+impl From<reqwest::Error> for SapiError {
+    fn from(e: reqwest::Error) -> Self {
+        // `Response::json()` reports a decode failure as a `reqwest::Error`
+        // with `is_decode() == true` rather than a bare `serde_json::Error`,
+        // so that's the only way to tell "SAPI is down" apart from "SAPI
+        // sent back something we didn't expect" at this `?` call site.
+        if e.is_decode() {
+            SapiError::Deserialize(e)
+        } else {
+            SapiError::Transport(e)
+        }
+    }
+}
+
+/// A credential for signing requests with the HTTP Signature scheme used by
+/// authenticated Triton/SAPI endpoints: a `Date` header plus an
+/// `Authorization: Signature ...` header computed over it with an RSA key
+/// identified by a key fingerprint.
+///
+/// See <https://tools.ietf.org/html/draft-cavage-http-signatures-12>.
+#[derive(Clone)]
+pub struct SigningCredential {
+    /// The `keyId` sent in the `Authorization` header, e.g.
+    /// `/<account login>/keys/<key fingerprint>`.
+    key_id: String,
+    key_pair: Arc<RsaKeyPair>,
+}
+
+impl fmt::Debug for SigningCredential {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SigningCredential")
+            .field("key_id", &self.key_id)
+            .finish()
+    }
+}
+
+impl SigningCredential {
+    /// Build a credential from an account login, a key fingerprint, and a
+    /// DER-encoded RSA private key, either bare (PKCS#1) or wrapped in PKCS#8.
+    pub fn new(
+        account_login: &str,
+        key_fingerprint: &str,
+        rsa_private_key_der: &[u8],
+    ) -> Result<Self, SapiError> {
+        let key_pair = RsaKeyPair::from_der(rsa_private_key_der)
+            .or_else(|_| RsaKeyPair::from_pkcs8(rsa_private_key_der))
+            .map_err(|_| SapiError::Signing("invalid RSA private key".into()))?;
+        Ok(SigningCredential {
+            key_id: format!("/{}/keys/{}", account_login, key_fingerprint),
+            key_pair: Arc::new(key_pair),
+        })
+    }
+
+    /// Sign `signing_string` (e.g. `"date: <rfc1123 date>"`) and base64
+    /// encode the result, as required by the `signature` parameter of the
+    /// `Authorization` header.
+    fn sign(&self, signing_string: &str) -> Result<String, SapiError> {
+        let rng = SystemRandom::new();
+        let mut signature = vec![0; self.key_pair.public_modulus_len()];
+        self.key_pair
+            .sign(
+                &signature::RSA_PKCS1_SHA256,
+                &rng,
+                signing_string.as_bytes(),
+                &mut signature,
+            )
+            .map_err(|_| SapiError::Signing("failed to sign request".into()))?;
+        Ok(base64::encode(&signature))
+    }
+
+    /// Build the `Date` and `Authorization` header values for a request
+    /// signing only the `date` header, as SAPI expects.
+    fn authorization_headers(&self) -> Result<(String, String), SapiError> {
+        let date = httpdate::fmt_http_date(SystemTime::now());
+        let signing_string = format!("date: {}", date);
+        let signature = self.sign(&signing_string)?;
+        let authorization = format!(
+            "Signature keyId=\"{}\",algorithm=\"rsa-sha256\",headers=\"date\",signature=\"{}\"",
+            self.key_id, signature
+        );
+        Ok((date, authorization))
+    }
+}
 
 /// Container for the zone metadata
 // XXX This structure is not as stable as the others below.
@@ -54,9 +191,10 @@ pub struct ServiceData {
     pub metadata: Option<Value>,
     #[serde(default)]
     pub master: bool,
-    // TODO: add the type field, which comes with sapi v2.0.
-    // In order to receive that field from sapi the "accept-version: 2" header
-    // field must be specified.
+    // Only present when talking to SAPI 2.0, which requires the
+    // "accept-version: 2" header.
+    #[serde(default, rename = "type")]
+    pub type_: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -65,7 +203,9 @@ pub struct InstanceData {
     pub service_uuid: String,
     pub params: Option<Value>,
     pub metadata: Option<Value>,
-    // TODO: add type field.  See above.
+    // See ServiceData::type_ above.
+    #[serde(default, rename = "type")]
+    pub type_: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -82,204 +222,860 @@ pub type Applications = Vec<ApplicationData>;
 pub type Services = Vec<ServiceData>;
 pub type Instances = Vec<InstanceData>;
 
-/// The SAPI client
-#[derive(Debug)]
-pub struct SAPI {
+/// Filter options for the list endpoints, serialized to a percent-encoded
+/// query string. Build one with `ListOptions::builder()`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ListOptions {
+    service_uuid: Option<String>,
+    application_uuid: Option<String>,
+    name: Option<String>,
+    master: Option<bool>,
+    include_master: Option<bool>,
+}
+
+impl ListOptions {
+    pub fn builder() -> ListOptionsBuilder {
+        ListOptionsBuilder::default()
+    }
+
+    /// Serialize to a leading-`?` query string, or an empty string if no
+    /// options were set.
+    fn to_query_string(&self) -> String {
+        let mut pairs = form_urlencoded::Serializer::new(String::new());
+
+        if let Some(v) = &self.service_uuid {
+            pairs.append_pair("service_uuid", v);
+        }
+        if let Some(v) = &self.application_uuid {
+            pairs.append_pair("application_uuid", v);
+        }
+        if let Some(v) = &self.name {
+            pairs.append_pair("name", v);
+        }
+        if let Some(v) = &self.master {
+            pairs.append_pair("master", &v.to_string());
+        }
+        if let Some(v) = &self.include_master {
+            pairs.append_pair("include_master", &v.to_string());
+        }
+
+        let encoded = pairs.finish();
+        if encoded.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", encoded)
+        }
+    }
+}
+
+/// Builder for `ListOptions`.
+#[derive(Debug, Default, Clone)]
+pub struct ListOptionsBuilder {
+    opts: ListOptions,
+}
+
+impl ListOptionsBuilder {
+    pub fn service_uuid(mut self, service_uuid: impl Into<String>) -> Self {
+        self.opts.service_uuid = Some(service_uuid.into());
+        self
+    }
+
+    pub fn application_uuid(mut self, application_uuid: impl Into<String>) -> Self {
+        self.opts.application_uuid = Some(application_uuid.into());
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.opts.name = Some(name.into());
+        self
+    }
+
+    pub fn master(mut self, master: bool) -> Self {
+        self.opts.master = Some(master);
+        self
+    }
+
+    pub fn include_master(mut self, include_master: bool) -> Self {
+        self.opts.include_master = Some(include_master);
+        self
+    }
+
+    pub fn build(self) -> ListOptions {
+        self.opts
+    }
+}
+
+/// Retry policy for transient failures: connection errors, timeouts, and
+/// 5xx/429 responses. Idempotent GET/DELETE requests retry by default;
+/// POST only retries if `retry_post` is set, since retrying a non-idempotent
+/// request risks duplicate side effects.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub retry_post: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            retry_post: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn is_retryable(error: &SapiError) -> bool {
+        match error {
+            SapiError::Transport(e) => e.is_timeout() || e.is_connect(),
+            SapiError::UnexpectedStatus { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            SapiError::Deserialize(_) | SapiError::NotFound | SapiError::Signing(_) => false,
+        }
+    }
+
+    /// How long to wait before the next attempt, honoring a `Retry-After`
+    /// header if the server sent one, otherwise backing off exponentially.
+    fn delay_for(&self, attempt: u32, error: &SapiError) -> Duration {
+        if let SapiError::UnexpectedStatus {
+            retry_after: Some(d),
+            ..
+        } = error
+        {
+            return *d;
+        }
+        // Cap the exponent so a caller-configured `max_attempts` in the
+        // dozens or more can't overflow `u32::pow`.
+        let exponent = attempt.saturating_sub(1).min(31);
+        self.base_delay * 2u32.pow(exponent)
+    }
+}
+
+/// Parse a `Retry-After` header's value (seconds form only; the HTTP-date
+/// form is not handled). Used by `AsyncSAPI::parse_retry_after`, which the
+/// blocking `SAPI` reaches through the `AsyncSAPI` it wraps.
+fn parse_retry_after_secs(value: &str) -> Option<Duration> {
+    value.parse::<u64>().ok().map(Duration::from_secs)
+}
+
+/// Configuration of an `AsyncSAPI`, and so of the blocking `SAPI` that wraps
+/// one: everything except the `reqwest` client itself.
+#[derive(Debug, Clone)]
+struct SapiConfig {
     sapi_base_url: String,
     request_timeout: u64,
-    client: Client, // reqwest client
     log: Logger,
+    api_version: u8,
+    signing_credential: Option<SigningCredential>,
+    retry_policy: RetryPolicy,
+}
+
+impl SapiConfig {
+    fn new(sapi_base_url: &str, request_timeout: u64, log: Logger) -> Self {
+        SapiConfig {
+            sapi_base_url: sapi_base_url.into(),
+            request_timeout,
+            log,
+            api_version: 1,
+            signing_credential: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+}
+
+/// The SAPI client: a blocking facade over `AsyncSAPI`. All endpoint,
+/// header, and retry logic lives once in `AsyncSAPI`; this just drives it
+/// to completion on a runtime owned by the client.
+pub struct SAPI {
+    inner: AsyncSAPI,
+    // `Runtime::block_on` takes `&mut self`, so a `SAPI` shared across
+    // threads needs this behind a `Mutex`.
+    runtime: Mutex<Runtime>,
+}
+
+impl fmt::Debug for SAPI {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SAPI").field("inner", &self.inner).finish()
+    }
 }
 
 impl SAPI {
     /// initialize SAPI client API
     pub fn new(sapi_base_url: &str, request_timeout: u64, log: Logger) -> Self {
-        let client = Client::builder()
+        let runtime = Runtime::new().expect("failed to start SAPI client's blocking runtime");
+        SAPI {
+            inner: AsyncSAPI::new(sapi_base_url, request_timeout, log),
+            runtime: Mutex::new(runtime),
+        }
+    }
+
+    /// Negotiate a specific SAPI API version (e.g. `2`) via the
+    /// `accept-version` header.  SAPI 1.0, the default, is assumed if this
+    /// is never called.
+    pub fn with_api_version(mut self, api_version: u8) -> Self {
+        self.inner = self.inner.with_api_version(api_version);
+        self
+    }
+
+    /// Sign requests with the given credential using the HTTP Signature
+    /// scheme. Without this, requests are sent unauthenticated, as before.
+    pub fn with_signing_credential(mut self, credential: SigningCredential) -> Self {
+        self.inner = self.inner.with_signing_credential(credential);
+        self
+    }
+
+    /// Retry transient failures per the given policy. Defaults to
+    /// `RetryPolicy::default()` if never called.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.inner = self.inner.with_retry_policy(retry_policy);
+        self
+    }
+
+    /// Drive a call into the shared `AsyncSAPI` implementation to
+    /// completion on this client's own runtime, blocking the caller.
+    fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.runtime
+            .lock()
+            .expect("SAPI runtime mutex poisoned")
+            .block_on(future)
+    }
+
+    /// Retrieve the "zone" configuration by zone UUID.
+    pub fn get_zone_config(&self, uuid: &str) -> Result<ZoneConfig, SapiError> {
+        self.block_on(self.inner.get_zone_config(uuid))
+    }
+
+    /// Get Instance
+    pub fn get_instance(&self, inst_uuid: &str) -> Result<InstanceData, SapiError> {
+        self.block_on(self.inner.get_instance(inst_uuid))
+    }
+
+    /// List all instances
+    pub fn list_instances(&self) -> Result<Instances, SapiError> {
+        self.block_on(self.inner.list_instances())
+    }
+
+    pub fn list_service_instances(&self, svc_uuid: &str) -> Result<Instances, SapiError> {
+        self.block_on(self.inner.list_service_instances(svc_uuid))
+    }
+
+    /// List instances matching the given filter options
+    pub fn list_instances_opts(&self, opts: &ListOptions) -> Result<Instances, SapiError> {
+        self.block_on(self.inner.list_instances_opts(opts))
+    }
+
+    /// create an instance of the service with the passed UUID
+    pub fn create_instance(
+        &self,
+        service_uuid: &str,
+        params: Option<Value>,
+        metadata: Option<Value>,
+    ) -> Result<AsyncResponse, SapiError> {
+        self.block_on(self.inner.create_instance(service_uuid, params, metadata))
+    }
+
+    /// modify the instance with the passed UUID with the contents of 'body'
+    pub fn update_instance(
+        &self,
+        inst_uuid: &str,
+        body: Value,
+    ) -> Result<AsyncResponse, SapiError> {
+        self.block_on(self.inner.update_instance(inst_uuid, body))
+    }
+
+    /// delete the instance with the passed UUID
+    pub fn delete_instance(&self, inst_uuid: &str) -> Result<AsyncResponse, SapiError> {
+        self.block_on(self.inner.delete_instance(inst_uuid))
+    }
+
+    /// List all services
+    pub fn list_services(&self) -> Result<Services, SapiError> {
+        self.block_on(self.inner.list_services())
+    }
+
+    /// get service by UUID
+    pub fn get_service(&self, uuid: &str) -> Result<ServiceData, SapiError> {
+        self.block_on(self.inner.get_service(uuid))
+    }
+
+    pub fn get_service_by_name(&self, name: &str) -> Result<Services, SapiError> {
+        self.block_on(self.inner.get_service_by_name(name))
+    }
+
+    /// List services matching the given filter options
+    pub fn list_services_opts(&self, opts: &ListOptions) -> Result<Services, SapiError> {
+        self.block_on(self.inner.list_services_opts(opts))
+    }
+
+    /// create the named service under the application with the passed UUID
+    pub fn create_service(
+        &self,
+        name: &str,
+        application_uuid: &str,
+    ) -> Result<AsyncResponse, SapiError> {
+        self.block_on(self.inner.create_service(name, application_uuid))
+    }
+
+    /// modify the named service with the contents of 'body'
+    pub fn update_service(
+        &self,
+        service_uuid: &str,
+        body: Value,
+    ) -> Result<AsyncResponse, SapiError> {
+        self.block_on(self.inner.update_service(service_uuid, body))
+    }
+
+    ///
+    pub fn delete_service(&self, service_uuid: &str) -> Result<AsyncResponse, SapiError> {
+        self.block_on(self.inner.delete_service(service_uuid))
+    }
+
+    pub fn get_application_by_name(&self, name: &str) -> Result<Applications, SapiError> {
+        self.block_on(self.inner.get_application_by_name(name))
+    }
+
+    pub fn list_applications(&self) -> Result<Applications, SapiError> {
+        self.block_on(self.inner.list_applications())
+    }
+
+    /// List applications matching the given filter options
+    pub fn list_applications_opts(&self, opts: &ListOptions) -> Result<Applications, SapiError> {
+        self.block_on(self.inner.list_applications_opts(opts))
+    }
+
+    pub fn get_application(&self, uuid: &str) -> Result<ApplicationData, SapiError> {
+        self.block_on(self.inner.get_application(uuid))
+    }
+
+    /// create the named application owned by the passed owner UUID
+    pub fn create_application(
+        &self,
+        name: &str,
+        owner_uuid: &str,
+        params: Option<Value>,
+        metadata: Option<Value>,
+    ) -> Result<AsyncResponse, SapiError> {
+        self.block_on(
+            self.inner
+                .create_application(name, owner_uuid, params, metadata),
+        )
+    }
+
+    /// modify the application with the passed UUID with the contents of 'body'
+    pub fn update_application(
+        &self,
+        app_uuid: &str,
+        body: Value,
+    ) -> Result<AsyncResponse, SapiError> {
+        self.block_on(self.inner.update_application(app_uuid, body))
+    }
+
+    /// delete the application with the passed UUID
+    pub fn delete_application(&self, app_uuid: &str) -> Result<AsyncResponse, SapiError> {
+        self.block_on(self.inner.delete_application(app_uuid))
+    }
+}
+
+/// Async variant of `SAPI`, built on reqwest's async `Client` rather than
+/// the blocking one, for callers running inside a tokio runtime.
+#[derive(Debug)]
+pub struct AsyncSAPI {
+    config: SapiConfig,
+    client: AsyncClient,
+}
+
+impl AsyncSAPI {
+    /// initialize an async SAPI client
+    pub fn new(sapi_base_url: &str, request_timeout: u64, log: Logger) -> Self {
+        let client = AsyncClient::builder()
             .timeout(Duration::from_secs(request_timeout))
             .build()
             .unwrap();
-        SAPI {
-            sapi_base_url: sapi_base_url.into(),
-            request_timeout,
+        AsyncSAPI {
+            config: SapiConfig::new(sapi_base_url, request_timeout, log),
             client,
-            log: log.clone(),
         }
     }
 
+    /// Negotiate a specific SAPI API version (e.g. `2`) via the
+    /// `accept-version` header.  SAPI 1.0, the default, is assumed if this
+    /// is never called.
+    pub fn with_api_version(mut self, api_version: u8) -> Self {
+        self.config.api_version = api_version;
+        self
+    }
+
+    /// Sign requests with the given credential using the HTTP Signature
+    /// scheme. Without this, requests are sent unauthenticated, as before.
+    pub fn with_signing_credential(mut self, credential: SigningCredential) -> Self {
+        self.config.signing_credential = Some(credential);
+        self
+    }
+
+    /// Retry transient failures per the given policy. Defaults to
+    /// `RetryPolicy::default()` if never called.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.config.retry_policy = retry_policy;
+        self
+    }
+
     /// Retrieve the "zone" configuration by zone UUID.
-    pub fn get_zone_config(&self, uuid: &str) -> Result<ZoneConfig, Box<dyn std::error::Error>> {
-        let url = format!("{}/configs/{}", self.sapi_base_url.clone(), uuid);
-        let zconfig: ZoneConfig = self.get(&url)?.json()?;
+    pub async fn get_zone_config(&self, uuid: &str) -> Result<ZoneConfig, SapiError> {
+        let url = format!("{}/configs/{}", self.config.sapi_base_url.clone(), uuid);
+        let zconfig: ZoneConfig = self.get(&url).await?.json().await?;
         Ok(zconfig)
     }
 
     /// Get Instance
-    pub fn get_instance(
-        &self,
-        inst_uuid: &str,
-    ) -> Result<InstanceData, Box<dyn std::error::Error>> {
-        let url = format!("{}/instances/{}", self.sapi_base_url.clone(), inst_uuid);
-        let instance: InstanceData = self.get(&url)?.json()?;
+    pub async fn get_instance(&self, inst_uuid: &str) -> Result<InstanceData, SapiError> {
+        let url = format!(
+            "{}/instances/{}",
+            self.config.sapi_base_url.clone(),
+            inst_uuid
+        );
+        let instance: InstanceData = self.get(&url).await?.json().await?;
         Ok(instance)
     }
 
     /// List all instances
-    pub fn list_instances(&self) -> Result<Instances, Box<dyn std::error::Error>> {
-        let url = format!("{}/instances", self.sapi_base_url.clone());
-        let instances: Instances = self.get(&url)?.json()?;
+    pub async fn list_instances(&self) -> Result<Instances, SapiError> {
+        let url = format!("{}/instances", self.config.sapi_base_url.clone());
+        let instances: Instances = self.get(&url).await?.json().await?;
         Ok(instances)
     }
 
-    pub fn list_service_instances(
-        &self,
-        svc_uuid: &str,
-    ) -> Result<Instances, Box<dyn std::error::Error>> {
+    pub async fn list_service_instances(&self, svc_uuid: &str) -> Result<Instances, SapiError> {
+        let opts = ListOptions::builder().service_uuid(svc_uuid).build();
+        self.list_instances_opts(&opts).await
+    }
+
+    /// List instances matching the given filter options
+    pub async fn list_instances_opts(&self, opts: &ListOptions) -> Result<Instances, SapiError> {
         let url = format!(
-            "{}/instances?service_uuid={}",
-            self.sapi_base_url.clone(),
-            svc_uuid
+            "{}/instances{}",
+            self.config.sapi_base_url.clone(),
+            opts.to_query_string()
         );
-        let instances: Instances = self.get(&url)?.json()?;
+        let instances: Instances = self.get(&url).await?.json().await?;
         Ok(instances)
     }
 
+    /// create an instance of the service with the passed UUID
+    pub async fn create_instance(
+        &self,
+        service_uuid: &str,
+        params: Option<Value>,
+        metadata: Option<Value>,
+    ) -> Result<AsyncResponse, SapiError> {
+        let body = json!({
+            "service_uuid": service_uuid,
+            "params": params,
+            "metadata": metadata
+        });
+        let url = format!("{}/instances", self.config.sapi_base_url.clone());
+        self.post(&url, &body).await
+    }
+
+    /// modify the instance with the passed UUID with the contents of 'body'
+    pub async fn update_instance(
+        &self,
+        inst_uuid: &str,
+        body: Value,
+    ) -> Result<AsyncResponse, SapiError> {
+        let url = format!(
+            "{}/instances/{}",
+            self.config.sapi_base_url.clone(),
+            inst_uuid
+        );
+        self.post(&url, &body).await
+    }
+
+    /// delete the instance with the passed UUID
+    pub async fn delete_instance(&self, inst_uuid: &str) -> Result<AsyncResponse, SapiError> {
+        let url = format!(
+            "{}/instances/{}",
+            self.config.sapi_base_url.clone(),
+            inst_uuid
+        );
+        self.delete(&url).await
+    }
+
     /// List all services
-    pub fn list_services(&self) -> Result<Services, Box<dyn std::error::Error>> {
-        let url = format!("{}/services", self.sapi_base_url.clone());
-        let sdata: Services = self.get(&url)?.json()?;
+    pub async fn list_services(&self) -> Result<Services, SapiError> {
+        let url = format!("{}/services", self.config.sapi_base_url.clone());
+        let sdata: Services = self.get(&url).await?.json().await?;
         Ok(sdata)
     }
 
     /// get service by UUID
-    pub fn get_service(&self, uuid: &str) -> Result<ServiceData, Box<dyn std::error::Error>> {
-        let url = format!("{}/services/{}", self.sapi_base_url.clone(), uuid);
-        let sdata: ServiceData = self.get(&url)?.json()?;
+    pub async fn get_service(&self, uuid: &str) -> Result<ServiceData, SapiError> {
+        let url = format!("{}/services/{}", self.config.sapi_base_url.clone(), uuid);
+        let sdata: ServiceData = self.get(&url).await?.json().await?;
         Ok(sdata)
     }
 
-    pub fn get_service_by_name(&self, name: &str) -> Result<Services, Box<dyn std::error::Error>> {
-        let url = format!("{}/services?name={}", self.sapi_base_url.clone(), name);
-        let sdata: Services = self.get(&url)?.json()?;
+    pub async fn get_service_by_name(&self, name: &str) -> Result<Services, SapiError> {
+        let opts = ListOptions::builder().name(name).build();
+        self.list_services_opts(&opts).await
+    }
+
+    /// List services matching the given filter options
+    pub async fn list_services_opts(&self, opts: &ListOptions) -> Result<Services, SapiError> {
+        let url = format!(
+            "{}/services{}",
+            self.config.sapi_base_url.clone(),
+            opts.to_query_string()
+        );
+        let sdata: Services = self.get(&url).await?.json().await?;
         Ok(sdata)
     }
 
     /// create the named service under the application with the passed UUID
-    pub fn create_service(
+    pub async fn create_service(
         &self,
         name: &str,
         application_uuid: &str,
-    ) -> Result<Response, Box<dyn std::error::Error>> {
+    ) -> Result<AsyncResponse, SapiError> {
         let body = json!({
             "name": name,
             "application_uuid": application_uuid
         });
-        let url = format!("{}/services", self.sapi_base_url.clone());
-        self.post(&url, &body)
+        let url = format!("{}/services", self.config.sapi_base_url.clone());
+        self.post(&url, &body).await
     }
 
     /// modify the named service with the contents of 'body'
-    pub fn update_service(
+    pub async fn update_service(
         &self,
         service_uuid: &str,
         body: Value,
-    ) -> Result<Response, Box<dyn std::error::Error>> {
-        let url = format!("{}/services/{}", self.sapi_base_url.clone(), service_uuid);
-        self.post(&url, &body)
+    ) -> Result<AsyncResponse, SapiError> {
+        let url = format!(
+            "{}/services/{}",
+            self.config.sapi_base_url.clone(),
+            service_uuid
+        );
+        self.post(&url, &body).await
     }
 
     ///
-    pub fn delete_service(
-        &self,
-        service_uuid: &str,
-    ) -> Result<Response, Box<dyn std::error::Error>> {
-        let url = format!("{}/services/{}", self.sapi_base_url.clone(), service_uuid);
-        self.delete(&url)
+    pub async fn delete_service(&self, service_uuid: &str) -> Result<AsyncResponse, SapiError> {
+        let url = format!(
+            "{}/services/{}",
+            self.config.sapi_base_url.clone(),
+            service_uuid
+        );
+        self.delete(&url).await
     }
 
-    pub fn get_application_by_name(
-        &self,
-        name: &str,
-    ) -> Result<Applications, Box<dyn std::error::Error>> {
-        let url = format!("{}/applications?name={}", self.sapi_base_url.clone(), name);
-        let apps: Applications = self.get(&url)?.json()?;
-        Ok(apps)
+    pub async fn get_application_by_name(&self, name: &str) -> Result<Applications, SapiError> {
+        let opts = ListOptions::builder().name(name).build();
+        self.list_applications_opts(&opts).await
     }
 
-    pub fn list_applications(&self) -> Result<Applications, Box<dyn std::error::Error>> {
-        let url = format!("{}/applications", self.sapi_base_url.clone());
-        let apps: Applications = self.get(&url)?.json()?;
+    pub async fn list_applications(&self) -> Result<Applications, SapiError> {
+        let url = format!("{}/applications", self.config.sapi_base_url.clone());
+        let apps: Applications = self.get(&url).await?.json().await?;
         Ok(apps)
     }
 
-    pub fn get_application(
+    /// List applications matching the given filter options
+    pub async fn list_applications_opts(
         &self,
-        uuid: &str,
-    ) -> Result<ApplicationData, Box<dyn std::error::Error>> {
-        let url = format!("{}/applications/{}", self.sapi_base_url.clone(), uuid);
+        opts: &ListOptions,
+    ) -> Result<Applications, SapiError> {
+        let url = format!(
+            "{}/applications{}",
+            self.config.sapi_base_url.clone(),
+            opts.to_query_string()
+        );
+        let apps: Applications = self.get(&url).await?.json().await?;
+        Ok(apps)
+    }
 
-        let app: ApplicationData = self.get(&url)?.json()?;
+    pub async fn get_application(&self, uuid: &str) -> Result<ApplicationData, SapiError> {
+        let url = format!(
+            "{}/applications/{}",
+            self.config.sapi_base_url.clone(),
+            uuid
+        );
+
+        let app: ApplicationData = self.get(&url).await?.json().await?;
         Ok(app)
     }
 
+    /// create the named application owned by the passed owner UUID
+    pub async fn create_application(
+        &self,
+        name: &str,
+        owner_uuid: &str,
+        params: Option<Value>,
+        metadata: Option<Value>,
+    ) -> Result<AsyncResponse, SapiError> {
+        let body = json!({
+            "name": name,
+            "owner_uuid": owner_uuid,
+            "params": params,
+            "metadata": metadata
+        });
+        let url = format!("{}/applications", self.config.sapi_base_url.clone());
+        self.post(&url, &body).await
+    }
+
+    /// modify the application with the passed UUID with the contents of 'body'
+    pub async fn update_application(
+        &self,
+        app_uuid: &str,
+        body: Value,
+    ) -> Result<AsyncResponse, SapiError> {
+        let url = format!(
+            "{}/applications/{}",
+            self.config.sapi_base_url.clone(),
+            app_uuid
+        );
+        self.post(&url, &body).await
+    }
+
+    /// delete the application with the passed UUID
+    pub async fn delete_application(&self, app_uuid: &str) -> Result<AsyncResponse, SapiError> {
+        let url = format!(
+            "{}/applications/{}",
+            self.config.sapi_base_url.clone(),
+            app_uuid
+        );
+        self.delete(&url).await
+    }
+
     //
     // private functions
     //
-    fn default_headers(&self) -> Headers {
-        let mut headers = Headers::new();
+    fn default_headers(&self) -> Result<HeaderMap, SapiError> {
+        let mut headers = HeaderMap::new();
 
-        headers.set(ContentType::json());
-        headers.set(Accept::json());
-        headers
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers.insert(
+            "accept-version",
+            HeaderValue::from_str(&self.config.api_version.to_string()).unwrap(),
+        );
+
+        if let Some(credential) = &self.config.signing_credential {
+            let (date, authorization) = credential.authorization_headers()?;
+            headers.insert(
+                "date",
+                HeaderValue::from_str(&date)
+                    .map_err(|_| SapiError::Signing("invalid date".into()))?,
+            );
+            headers.insert(
+                "authorization",
+                HeaderValue::from_str(&authorization)
+                    .map_err(|_| SapiError::Signing("invalid signature".into()))?,
+            );
+        }
+
+        Ok(headers)
     }
 
-    /// Generic get -- results deserialized by caller
-    fn get<S>(&self, url: S) -> Result<Response, Box<dyn std::error::Error>>
+    /// Map a response to an error if its status is not 2xx, capturing the
+    /// body and any `Retry-After` hint for diagnostics.
+    async fn check_status(response: AsyncResponse) -> Result<AsyncResponse, SapiError> {
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        if status == StatusCode::NOT_FOUND {
+            return Err(SapiError::NotFound);
+        }
+
+        let retry_after = AsyncSAPI::parse_retry_after(&response);
+        let body = response.text().await.unwrap_or_default();
+        Err(SapiError::UnexpectedStatus {
+            status,
+            body,
+            retry_after,
+        })
+    }
+
+    fn parse_retry_after(response: &AsyncResponse) -> Option<Duration> {
+        response
+            .headers()
+            .get("retry-after")
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after_secs)
+    }
+
+    /// Send a request, retrying per `self.config.retry_policy` on transient
+    /// failures. `idempotent` requests (GET/DELETE) retry by default; others
+    /// (POST) only retry if the policy opts in, to avoid duplicating
+    /// side effects.
+    async fn send_with_retry<F, Fut>(
+        &self,
+        idempotent: bool,
+        mut send: F,
+    ) -> Result<AsyncResponse, SapiError>
     where
-        S: IntoUrl,
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<AsyncResponse, SapiError>>,
     {
-        match self
-            .client
-            .get(url)
-            .headers_011(self.default_headers())
-            .send()
-        {
-            Ok(response) => Ok(response),
-            Err(e) => Err(Box::new(e)),
+        let retryable = idempotent || self.config.retry_policy.retry_post;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let result = async { AsyncSAPI::check_status(send().await?).await }.await;
+            match result {
+                Ok(resp) => return Ok(resp),
+                Err(e) => {
+                    if !retryable
+                        || attempt >= self.config.retry_policy.max_attempts
+                        || !RetryPolicy::is_retryable(&e)
+                    {
+                        return Err(e);
+                    }
+                    tokio::time::delay_for(self.config.retry_policy.delay_for(attempt, &e)).await;
+                }
+            }
         }
     }
 
+    /// Generic get -- results deserialized by caller
+    async fn get<S>(&self, url: S) -> Result<AsyncResponse, SapiError>
+    where
+        S: IntoUrl + Clone,
+    {
+        self.send_with_retry(true, || async {
+            Ok(self
+                .client
+                .get(url.clone())
+                .headers(self.default_headers()?)
+                .send()
+                .await?)
+        })
+        .await
+    }
+
     /// Generic post
-    fn post<S>(&self, url: S, body: &Value) -> Result<Response, Box<dyn std::error::Error>>
+    async fn post<S>(&self, url: S, body: &Value) -> Result<AsyncResponse, SapiError>
     where
-        S: IntoUrl,
+        S: IntoUrl + Clone,
     {
-        let resp = self
-            .client
-            .post(url)
-            .headers_011(self.default_headers())
-            .json(&body)
-            .send()?;
-        Ok(resp)
+        self.send_with_retry(false, || async {
+            Ok(self
+                .client
+                .post(url.clone())
+                .headers(self.default_headers()?)
+                .json(&body)
+                .send()
+                .await?)
+        })
+        .await
     }
 
     /// Generic delete
-    fn delete<S>(&self, url: S) -> Result<Response, Box<dyn std::error::Error>>
+    async fn delete<S>(&self, url: S) -> Result<AsyncResponse, SapiError>
     where
-        S: IntoUrl,
+        S: IntoUrl + Clone,
     {
-        let resp = self
-            .client
-            .delete(url)
-            .headers_011(self.default_headers())
-            .send()?;
-        Ok(resp)
+        self.send_with_retry(true, || async {
+            Ok(self
+                .client
+                .delete(url.clone())
+                .headers(self.default_headers()?)
+                .send()
+                .await?)
+        })
+        .await
     }
 }
 
+#[test]
+fn test_list_options_to_query_string() {
+    assert_eq!(ListOptions::default().to_query_string(), "");
+
+    let opts = ListOptions::builder().service_uuid("abc").build();
+    assert_eq!(opts.to_query_string(), "?service_uuid=abc");
+
+    let opts = ListOptions::builder()
+        .service_uuid("abc")
+        .application_uuid("def")
+        .name("cheddar & brie")
+        .master(true)
+        .include_master(false)
+        .build();
+    assert_eq!(
+        opts.to_query_string(),
+        "?service_uuid=abc&application_uuid=def&name=cheddar+%26+brie&master=true&include_master=false"
+    );
+}
+
+#[test]
+fn test_retry_policy_is_retryable() {
+    let server_error = SapiError::UnexpectedStatus {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        body: String::new(),
+        retry_after: None,
+    };
+    assert!(RetryPolicy::is_retryable(&server_error));
+
+    let too_many_requests = SapiError::UnexpectedStatus {
+        status: StatusCode::TOO_MANY_REQUESTS,
+        body: String::new(),
+        retry_after: None,
+    };
+    assert!(RetryPolicy::is_retryable(&too_many_requests));
+
+    let bad_request = SapiError::UnexpectedStatus {
+        status: StatusCode::BAD_REQUEST,
+        body: String::new(),
+        retry_after: None,
+    };
+    assert!(!RetryPolicy::is_retryable(&bad_request));
+
+    assert!(!RetryPolicy::is_retryable(&SapiError::NotFound));
+    assert!(!RetryPolicy::is_retryable(&SapiError::Signing(
+        "bad key".into()
+    )));
+}
+
+#[test]
+fn test_retry_policy_delay_for() {
+    let policy = RetryPolicy {
+        max_attempts: 40,
+        base_delay: Duration::from_millis(100),
+        retry_post: false,
+    };
+    let error = SapiError::UnexpectedStatus {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        body: String::new(),
+        retry_after: None,
+    };
+
+    assert_eq!(policy.delay_for(1, &error), Duration::from_millis(100));
+    assert_eq!(policy.delay_for(2, &error), Duration::from_millis(200));
+    assert_eq!(policy.delay_for(3, &error), Duration::from_millis(400));
+
+    // A large attempt count must not overflow 2u32.pow's exponent.
+    let _ = policy.delay_for(100, &error);
+
+    let with_retry_after = SapiError::UnexpectedStatus {
+        status: StatusCode::TOO_MANY_REQUESTS,
+        body: String::new(),
+        retry_after: Some(Duration::from_secs(5)),
+    };
+    assert_eq!(
+        policy.delay_for(1, &with_retry_after),
+        Duration::from_secs(5)
+    );
+}
+
 #[test]
 fn test_services() {
     use slog::{error, info, o, Drain, Logger};
-    use std::sync::Mutex;
 
     let plain = slog_term::PlainSyncDecorator::new(std::io::stdout());
     let log = Logger::root(
@@ -317,3 +1113,91 @@ fn test_services() {
         Err(e) => error!(log, "error: {:?}", e),
     }
 }
+
+#[test]
+fn test_signing_credential_and_default_headers() {
+    use slog::{o, Discard, Logger};
+
+    // A throwaway 2048-bit RSA key (PKCS#8 DER, base64'd to keep this test
+    // self-contained) used only to exercise the signing path.
+    const TEST_RSA_PRIVATE_KEY_DER_BASE64: &str = "\
+        MIIEvgIBADANBgkqhkiG9w0BAQEFAASCBKgwggSkAgEAAoIBAQDE0y2k/qtv7FxYgAakzozECJda\
+        89dXwTr3pKBTlvYtRszPF1ijtE0ZETYHcWtrHGajPL9cq/q8wrQDlVMhfE5Z9dsWIy6gtudocEbI\
+        HChbRGU2mNBit2BFImvgBBDmHKFj/1eDCmV9c++ZF9ClxK2KOEvjH7jWnYTBNv2kjxOHJ7czsdst\
+        YIcquh2mtOoEZoAIyeJlOR2o3GCT4nmtMnorct3Z2YKuH1YngpbLXgzga/DuQqyvLIAJNgle394G\
+        yGJTcm9yhendZ6ZLTDiedkIJf9QZfiJmZWF13UcTksrnxFn1cArzYN2pkQRtsQE5oIqOSK+KVe1Q\
+        hKF/cSy7LuVFAgMBAAECggEACpoujgH65aVHhyvkT7nhmsi0sH1MlwlNXhwj18Tcv20eKLSi/XR/\
+        p69F6Ao7xVILFFV4IGuSLttVG5FOYFIkHVPxSIS+JQGdPwK3SV287cemJRX/uaWTnb3ALlnBuEeI\
+        CQ0u31vuwh595FMEVf2ddIHaUqJ5kDP9e/P0n0nmvSO2G2lkpbTirkDV1f2vmYklXheHclzEsOkU\
+        bbRrJHW/FFZ/cAID9Y4AN+4Wz2xRVhRT2AEnDjKEUJtMTyfGx//09q8Ph9R1ErwLPyHOamX89lG0\
+        8PsECRtIC4VUPPmOoY537DGsYghs+PrSlrOMAl/MnB9rSHOMoZXbyhR9kEUHKQKBgQDhfty6hXD0\
+        L2Vbin0nCIqUY5KWgdmEiZ+10sLEHBOicOn2G70cZuqkshJeFTyhzTDDNHONbBq20V/DBJdH9OqY\
+        g78GTphBlqhaLLa6cH3B6qUeQp70dDfglPAktVNzAYV00ZoTZnUbmKE45vzSp11EZdpFR7j7R3nI\
+        vSH189OGbQKBgQDfc2xIem36V+o1HYDelAjQok4CYgHZW7tmjOxhAPdtZVKgBhYqX+/NHEsz6AfN\
+        H9ZL0GXqPtcVVTvuLSwcL7nJZvxdT13K2kXVj51W3GpJ0ecJi6z6ocnthafhdlsStfX7TMzqPzc1\
+        74WJLBXKnRTiDhsZ1MNoxUuBADfH5p9zOQKBgQCp6ROdIAt6k3QGRBYFoXP6WAUOj409Rw4mzUag\
+        yp8o2XgRT9lxW0MXEm6wocPsD1bc39rqBzkh5CYKJp1vROa4QR1hPTuQwFreh66wUS8RPSNp570q\
+        aZ/pA5DnuBHHKLBM09sLHzrCroFdBQAgIqgub6jGZ0LFZJgpYAoE79LFCQKBgE8kZnnVQxakBMQD\
+        Sx2KeQTMN60IYXdG0j0kNKS23uvUM0uSXE9t41VWbBxaVkkEubXoffBViYB4y900CqeBD+CRpVi3\
+        P5UVch6N20BkmRDEU3xCCGZM32RNLoTgkqkx4Ym8Tee5tChaSYWoI5+7uyXFsTuY7A0zs+Zg2WDo\
+        AhmhAoGBAJc/wJcNZEQ8/rpLXpClLLXyMNgNqSl4N3dwmqCX8zvyun/srKq0EoYo3yeMKHRmucIE\
+        +VntoYUvlBFqJt0nrzdWpor8WLeXODfUiW0Rhg3u3uxCFALeH9ZTwZhxJZ6u835hrIwy8wsBpc4f\
+        B7m1Wi4LVvWXXCRj376oYmI+1j59";
+    let der = base64::decode(TEST_RSA_PRIVATE_KEY_DER_BASE64).unwrap();
+    let credential = SigningCredential::new("admin", "aa:bb:cc:dd", &der).unwrap();
+
+    let (date, authorization) = credential.authorization_headers().unwrap();
+    assert!(!date.is_empty());
+    assert!(authorization.starts_with(
+        "Signature keyId=\"/admin/keys/aa:bb:cc:dd\",algorithm=\"rsa-sha256\",headers=\"date\",signature=\""
+    ));
+
+    let log = Logger::root(Discard, o!());
+
+    let unsigned = AsyncSAPI::new("http://10.77.77.136", 60, log.clone());
+    let headers = unsigned.default_headers().unwrap();
+    assert!(!headers.contains_key("date"));
+    assert!(!headers.contains_key("authorization"));
+
+    let signed = AsyncSAPI::new("http://10.77.77.136", 60, log).with_signing_credential(credential);
+    let headers = signed.default_headers().unwrap();
+    assert!(!headers.get("date").unwrap().to_str().unwrap().is_empty());
+    assert!(headers
+        .get("authorization")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("Signature keyId=\"/admin/keys/aa:bb:cc:dd\",algorithm=\"rsa-sha256\","));
+}
+
+#[test]
+fn test_service_and_instance_data_type_roundtrip() {
+    let service: ServiceData = serde_json::from_str(
+        r#"{"uuid":"u","name":"n","application_uuid":"a","params":null,"metadata":null,"type":"vm"}"#,
+    )
+    .unwrap();
+    assert_eq!(service.type_, Some("vm".to_string()));
+    let reencoded: Value = serde_json::from_str(&serde_json::to_string(&service).unwrap()).unwrap();
+    assert_eq!(reencoded["type"], "vm");
+
+    // SAPI 1.0 payloads predate this field and omit it entirely.
+    let service_1_0: ServiceData = serde_json::from_str(
+        r#"{"uuid":"u","name":"n","application_uuid":"a","params":null,"metadata":null}"#,
+    )
+    .unwrap();
+    assert_eq!(service_1_0.type_, None);
+
+    let instance: InstanceData = serde_json::from_str(
+        r#"{"uuid":"u","service_uuid":"s","params":null,"metadata":null,"type":"vm"}"#,
+    )
+    .unwrap();
+    assert_eq!(instance.type_, Some("vm".to_string()));
+    let reencoded: Value =
+        serde_json::from_str(&serde_json::to_string(&instance).unwrap()).unwrap();
+    assert_eq!(reencoded["type"], "vm");
+
+    let instance_1_0: InstanceData =
+        serde_json::from_str(r#"{"uuid":"u","service_uuid":"s","params":null,"metadata":null}"#)
+            .unwrap();
+    assert_eq!(instance_1_0.type_, None);
+}